@@ -0,0 +1,198 @@
+use crate::digest::{hash_hex, hex_ct_eq, hmac_hex, DigestState};
+use crate::{RCPConfig, Request};
+
+/// Incrementally hashes one part of a large request, feeding its sorted pairs into the digest
+/// chunk-by-chunk instead of materializing the whole assembled string first.
+///
+/// A part's own checksum is an unkeyed structural digest, the streaming equivalent of assembling
+/// one part's worth of pairs before hashing - it is *not* authenticated on its own. Authentication
+/// happens once, the same way [`RCPConfig::get_checksum`] does it: feed
+/// every part through this builder, then pass their checksums to [`combine`], which is what
+/// keys the result with `shared_secret`. This gives a streamed checksum the same security
+/// properties as the non-streaming one, while still only ever hashing one part's pairs at a
+/// time.
+///
+/// Pairs must be fed in the same key-sorted order `get_checksum` would use for the part they
+/// belong to; `ChecksumBuilder` does not sort on its own, since buffering everything to sort it
+/// would defeat the point of streaming.
+pub struct ChecksumBuilder<'a> {
+    config: &'a RCPConfig,
+    state: DigestState,
+}
+impl<'a> ChecksumBuilder<'a> {
+    /// Start hashing a new part using `config`'s algorithm and canonicalization settings.
+    pub fn new(config: &'a RCPConfig) -> Self {
+        ChecksumBuilder {
+            config,
+            state: DigestState::new(config.algorithm),
+        }
+    }
+
+    /// Feed this part's next chunk of already key-sorted pairs into the digest.
+    pub fn update<R: Request>(&mut self, request: R) {
+        for (key, value) in request.into_pairs() {
+            append_field(&mut self.state, key.as_ref(), self.config.canonical);
+            append_field(&mut self.state, &value.to_string(), self.config.canonical);
+        }
+    }
+
+    /// Finalize this part and return its (unkeyed) checksum as a lowercase hex string.
+    pub fn finish(self) -> String {
+        self.state.finish_hex()
+    }
+}
+
+/// Fold `salt` and a sequence of per-part checksums (as produced by [`ChecksumBuilder::finish`])
+/// into one checksum for the whole request, authenticated with `config.shared_secret` the same
+/// way [`RCPConfig::get_checksum`] authenticates a non-streamed request - via HMAC if
+/// `config.use_hmac`, or by appending the secret before the final hash otherwise.
+///
+/// `part_checksums` must be passed in the same order the parts appear in the request. Every part
+/// must have been hashed with the same `config.algorithm`: since an algorithm always produces a
+/// fixed-width hex digest, concatenating same-width checksums back to back is unambiguous - two
+/// different ways of partitioning the same pairs into parts cannot produce the same sequence of
+/// checksums, so there is no need to length-prefix them the way `pre_assemble` has to for
+/// arbitrary-width keys and values. With `config.canonical` set they are length-prefixed anyway,
+/// like the salt and every other field.
+///
+/// # Panics
+///
+/// Panics if `config.use_time_component` is `true`. Unlike [`RCPConfig::get_checksum`], this
+/// streaming API has nowhere to fold a timestamp in - `ChecksumBuilder` only ever sees one
+/// part's pairs at a time - so honoring `use_time_component` silently here would produce a
+/// checksum with none of the freshness/replay protection its caller asked for. Set
+/// `use_time_component` to `false` on the `RCPConfig` used for the streaming API, the same way
+/// [`crate::ReplayGuard`] requires it to be `true`.
+pub fn combine(config: &RCPConfig, salt: &str, part_checksums: &[String]) -> String {
+    assert!(
+        !config.use_time_component,
+        "combine/validate have no way to fold RCPConfig::use_time_component's timestamp into a \
+         streamed checksum; set it to false on the RCPConfig used for the multipart API instead.",
+    );
+
+    let mut message = String::new();
+    append_message_field(&mut message, salt, config.canonical);
+    for part_checksum in part_checksums {
+        append_message_field(&mut message, part_checksum, config.canonical);
+    }
+
+    if config.use_hmac {
+        hmac_hex(config.algorithm, config.shared_secret.as_bytes(), message)
+    } else {
+        append_message_field(&mut message, &config.shared_secret, config.canonical);
+        hash_hex(config.algorithm, message)
+    }
+}
+
+/// Like [`combine`], but compares the result against `checksum` in constant time instead of
+/// returning it, mirroring [`RCPConfig::validate_checksum`].
+///
+/// # Panics
+///
+/// Panics if `config.use_time_component` is `true`; see [`combine`].
+pub fn validate(config: &RCPConfig, salt: &str, part_checksums: &[String], checksum: &str) -> bool {
+    hex_ct_eq(&combine(config, salt, part_checksums), checksum).into()
+}
+
+fn append_field(state: &mut DigestState, field: &str, canonical: bool) {
+    if canonical {
+        state.update(format!("{}:", field.len()));
+    }
+    state.update(field);
+}
+
+fn append_message_field(string: &mut String, field: &str, canonical: bool) {
+    if canonical {
+        string.push_str(&field.len().to_string());
+        string.push(':');
+    }
+    string.push_str(field);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{combine, validate, ChecksumBuilder, RCPConfig};
+
+    fn config() -> RCPConfig {
+        RCPConfig {
+            use_time_component: false,
+            shared_secret: "Hallo-123".to_string(),
+            canonical: true,
+            ..RCPConfig::default()
+        }
+    }
+
+    /// Feeding a part's pairs in one chunk or split across several `update` calls must hash to
+    /// the same checksum, since that is the whole point of a chunk-by-chunk builder.
+    #[test]
+    fn chunking_does_not_affect_the_result() {
+        let config = config();
+
+        let mut whole = ChecksumBuilder::new(&config);
+        whole.update([("a", " long test"), ("b", "test")]);
+        let whole = whole.finish();
+
+        let mut chunked = ChecksumBuilder::new(&config);
+        chunked.update([("a", " long test")]);
+        chunked.update([("b", "test")]);
+        let chunked = chunked.finish();
+
+        assert_eq!(whole, chunked);
+    }
+
+    /// `combine` must be deterministic, sensitive to the order its parts are passed in, and
+    /// actually keyed with the shared secret (changing it changes the result).
+    #[test]
+    fn combine_is_order_sensitive_and_keyed() {
+        let config = config();
+
+        let mut first = ChecksumBuilder::new(&config);
+        first.update([("a", " long test")]);
+        let first = first.finish();
+
+        let mut second = ChecksumBuilder::new(&config);
+        second.update([("b", "test")]);
+        let second = second.finish();
+
+        let forward = combine(&config, "TestSalt", &[first.clone(), second.clone()]);
+        let backward = combine(&config, "TestSalt", &[second.clone(), first.clone()]);
+        assert_ne!(forward, backward);
+
+        let other_secret = RCPConfig {
+            shared_secret: "different".to_string(),
+            ..config.clone()
+        };
+        assert_ne!(forward, combine(&other_secret, "TestSalt", &[first, second]));
+    }
+
+    /// `validate` must accept a checksum produced by `combine` and reject a tampered one.
+    #[test]
+    fn validate_round_trips() {
+        let config = config();
+
+        let mut part = ChecksumBuilder::new(&config);
+        part.update([("a", " long test"), ("b", "test")]);
+        let part = part.finish();
+
+        let checksum = combine(&config, "TestSalt", std::slice::from_ref(&part));
+        assert!(validate(&config, "TestSalt", std::slice::from_ref(&part), &checksum));
+        assert!(!validate(&config, "TestSalt", &[part], "not-a-real-checksum"));
+    }
+
+    /// `combine` has no way to fold a timestamp into a streamed checksum, so it refuses to run
+    /// rather than silently dropping `use_time_component`'s freshness/replay protection.
+    #[test]
+    #[should_panic(expected = "use_time_component")]
+    fn panics_with_time_component() {
+        let config = RCPConfig {
+            use_time_component: true,
+            ..config()
+        };
+
+        let mut part = ChecksumBuilder::new(&config);
+        part.update([("a", " long test")]);
+        let part = part.finish();
+
+        combine(&config, "TestSalt", &[part]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest as _, Sha256, Sha512};
+use sha3::Sha3_256;
+use subtle::{Choice, ConstantTimeEq};
+
+/// Digest algorithm used to compute a checksum.
+///
+/// `Sha512` is the default and matches the protocol's original, hard-coded behaviour,
+/// so existing checksums keep validating unless a partner opts into something else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    Sha256,
+    #[default]
+    Sha512,
+    Sha3_256,
+    Blake3,
+}
+
+/// Hash `data` with the selected `algorithm` and return its lowercase hex representation.
+pub fn hash_hex(algorithm: Algorithm, data: impl AsRef<[u8]>) -> String {
+    let mut state = DigestState::new(algorithm);
+    state.update(data);
+    state.finish_hex()
+}
+
+/// An in-progress hash, fed incrementally instead of all at once.
+///
+/// This is what lets [`crate::ChecksumBuilder`] hash a request's pairs chunk-by-chunk without
+/// ever materializing the whole assembled string.
+pub enum DigestState {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Sha3_256(Sha3_256),
+    // `blake3::Hasher` is far larger than the other variants, so box it to keep `DigestState`
+    // from inflating to blake3's size for every algorithm.
+    Blake3(Box<blake3::Hasher>),
+}
+impl DigestState {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => DigestState::Sha256(Sha256::new()),
+            Algorithm::Sha512 => DigestState::Sha512(Sha512::new()),
+            Algorithm::Sha3_256 => DigestState::Sha3_256(Sha3_256::new()),
+            Algorithm::Blake3 => DigestState::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    /// Feed another chunk of data into the hash.
+    pub fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            DigestState::Sha256(hasher) => hasher.update(data),
+            DigestState::Sha512(hasher) => hasher.update(data),
+            DigestState::Sha3_256(hasher) => hasher.update(data),
+            DigestState::Blake3(hasher) => {
+                hasher.update(data.as_ref());
+            }
+        }
+    }
+
+    /// Finalize the hash and return its lowercase hex representation.
+    pub fn finish_hex(self) -> String {
+        let bytes: Vec<u8> = match self {
+            DigestState::Sha256(hasher) => hasher.finalize().to_vec(),
+            DigestState::Sha512(hasher) => hasher.finalize().to_vec(),
+            DigestState::Sha3_256(hasher) => hasher.finalize().to_vec(),
+            DigestState::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+
+        to_hex(&bytes)
+    }
+}
+
+/// Compute `HMAC(key, data)` with the selected `algorithm` and return its lowercase hex
+/// representation.
+///
+/// Unlike [`hash_hex`], `key` is never concatenated into `data`: it authenticates the message
+/// the way a keyed-MAC is meant to, instead of merely being hashed alongside it.
+pub fn hmac_hex(algorithm: Algorithm, key: &[u8], data: impl AsRef<[u8]>) -> String {
+    let bytes: Vec<u8> = match algorithm {
+        Algorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+            mac.update(data.as_ref());
+            mac.finalize().into_bytes().to_vec()
+        }
+        Algorithm::Sha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+            mac.update(data.as_ref());
+            mac.finalize().into_bytes().to_vec()
+        }
+        Algorithm::Sha3_256 => {
+            let mut mac = Hmac::<Sha3_256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+            mac.update(data.as_ref());
+            mac.finalize().into_bytes().to_vec()
+        }
+        Algorithm::Blake3 => {
+            // blake3's native keyed hash wants a 32 byte key, so derive one from the secret.
+            let key = blake3::hash(key);
+            blake3::keyed_hash(key.as_bytes(), data.as_ref()).as_bytes().to_vec()
+        }
+    };
+
+    to_hex(&bytes)
+}
+
+/// Render a byte slice as a lowercase hex string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut string = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(string, "{:02x}", byte).unwrap();
+    }
+    string
+}
+
+/// Decode a hex string (digits of either case) into raw bytes.
+///
+/// Returns `None` if `hex` has an odd length or contains a non-hex digit.
+///
+/// `hex` may be attacker-controlled (it is the value being authenticated), so this works on
+/// bytes rather than slicing the `&str` by index: a multi-byte UTF-8 character does not
+/// necessarily align to a 2-byte boundary, and slicing through one would panic instead of
+/// simply failing to decode.
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let bytes = hex.as_bytes();
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            // `from_str_radix` also accepts a leading `+`, so check for hex digits explicitly.
+            if !pair.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+/// Compare two hex strings (digits of either case) for equality without leaking timing information about
+/// where (or whether) they differ.
+///
+/// Decoding failures and length mismatches are not secret - only the content, once both sides
+/// are known to be the same length, is compared in constant time.
+pub fn hex_ct_eq(a: &str, b: &str) -> Choice {
+    match (from_hex(a), from_hex(b)) {
+        (Some(a), Some(b)) if a.len() == b.len() => a.ct_eq(&b),
+        _ => Choice::from(0),
+    }
+}
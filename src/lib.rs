@@ -1,6 +1,15 @@
 use std::fmt::{Write, Display};
-use sha2::{Sha512, Digest};
 use chrono::Utc;
+use subtle::{Choice, ConditionallySelectable};
+
+mod builder;
+mod digest;
+mod replay;
+
+pub use builder::{combine, validate, ChecksumBuilder};
+pub use digest::Algorithm;
+pub use replay::ReplayGuard;
+use digest::{hash_hex, hex_ct_eq, hmac_hex};
 
 /// Parameters for communicating with a single partner.
 #[derive(Debug, Clone)]
@@ -28,6 +37,30 @@ pub struct RCPConfig {
     /// longer than you want a validation to.
     /// *This protocol was never designed to handle time spans above a few seconds.*
     pub time_delta: i64,
+
+    /// Which digest algorithm to hash the checksum with.
+    ///
+    /// Defaults to `Sha512` to match this protocol's original, hard-coded behaviour.
+    /// Both partners need to agree on this value, the same way they agree on `shared_secret`.
+    pub algorithm: Algorithm,
+
+    /// Whether to authenticate the checksum with `HMAC(shared_secret, ..)` instead of the
+    /// legacy `hash(.. ‖ shared_secret)` construction.
+    ///
+    /// A keyed-MAC is the textbook-correct way to authenticate a message with a shared secret
+    /// and avoids the subtle pitfalls of a plain concatenated hash. This defaults to `false` so
+    /// the legacy mode remains available for interop with the existing Python reference
+    /// implementation; both partners need to agree on this value.
+    pub use_hmac: bool,
+
+    /// Whether to length-prefix every key and value instead of concatenating them directly.
+    ///
+    /// The legacy `key1value1key2value2...` concatenation is ambiguous: the pairs `("ab", "c")`
+    /// and `("a", "bc")` assemble to the same string and therefore hash identically, and a
+    /// crafted value can be made to look like an adjacent key. Setting this to `true` frames
+    /// each field as `len:field` instead, so distinct pair sets can never collide. This defaults
+    /// to `false` for backward compatibility; both partners need to agree on this value.
+    pub canonical: bool,
 }
 impl Default for RCPConfig {
     /// ```hidden
@@ -35,13 +68,19 @@ impl Default for RCPConfig {
     ///     shared_secret: "".to_string(),
     ///     use_time_component: true,
     ///     time_delta: 5,
+    ///     algorithm: Algorithm::Sha512,
+    ///     use_hmac: false,
+    ///     canonical: false,
     /// }
     /// ```
     fn default() -> Self {
         RCPConfig {
             shared_secret: String::new(),
             use_time_component: true,
-            time_delta: 5
+            time_delta: 5,
+            algorithm: Algorithm::default(),
+            use_hmac: false,
+            canonical: false,
         }
     }
 }
@@ -50,73 +89,105 @@ impl RCPConfig {
     ///
     /// When writing HTTP APIs it is a good rule of thumb to use a request's endpoint as salt.
     pub fn get_checksum<R: Request>(&self, request: R, salt: &str) -> String {
-        let mut string = pre_assemble(request, &self.shared_secret, salt);
+        let mut string = pre_assemble(request, &self.shared_secret, salt, !self.use_hmac, self.canonical);
 
         if self.use_time_component {
             // Append current utc timestamp (unix epoch - just seconds)
             write!(string, "{}", Utc::now().timestamp()).unwrap();
         }
 
-        // Hash with SHA512
-        // Represent the hash as hex string (lowercase)
-        sha512(string)
+        // Hash (or HMAC, if configured) with the configured algorithm
+        // Represent the result as hex string (lowercase)
+        if self.use_hmac {
+            hmac_hex(self.algorithm, self.shared_secret.as_bytes(), string)
+        } else {
+            hash_hex(self.algorithm, string)
+        }
     }
 
     /// Check whether or not a checksum matches a given payload and salt.
     ///
     /// If your not using timestamps, this will basically to a `get_checksum(..) == checksum`.
     /// If you are, it iterates over the `time_delta` checking multiple timestamps.
+    ///
+    /// The comparison is constant-time and, when timestamps are used, every candidate in
+    /// `time_delta`'s range is checked regardless of whether an earlier one already matched.
+    /// This keeps both *which* candidate matched and *whether any did* from leaking through
+    /// how long the call takes.
     pub fn validate_checksum<R: Request>(&self, request: R, salt: &str, checksum: &str) -> bool {
+        self.matching_timestamp(request, salt, checksum).is_some()
+    }
+
+    /// Like [`validate_checksum`](Self::validate_checksum), but on a match also returns the
+    /// timestamp the checksum matched against, so callers such as [`ReplayGuard`] can track
+    /// which timestamps have already been used.
+    ///
+    /// Returns `Some(0)` on a match when `use_time_component` is `false`, since no timestamp is
+    /// part of the checksum to report in that case.
+    pub fn matching_timestamp<R: Request>(&self, request: R, salt: &str, checksum: &str) -> Option<i64> {
         if self.use_time_component {
-            let string = pre_assemble(request, &self.shared_secret, salt);
+            let string = pre_assemble(request, &self.shared_secret, salt, !self.use_hmac, self.canonical);
             let now = Utc::now().timestamp();
+            let mut matched = Choice::from(0);
+            let mut matched_timestamp = 0;
             for delta in (-self.time_delta)..(self.time_delta) {
-                let string = format!("{}{}", string, now + delta);
-                if sha512(string) == checksum {
-                    return true;
-                }
+                let candidate = now + delta;
+                let string = format!("{}{}", string, candidate);
+                let computed = if self.use_hmac {
+                    hmac_hex(self.algorithm, self.shared_secret.as_bytes(), string)
+                } else {
+                    hash_hex(self.algorithm, string)
+                };
+                let this_matched = hex_ct_eq(&computed, checksum);
+                matched_timestamp = i64::conditional_select(&matched_timestamp, &candidate, this_matched);
+                matched |= this_matched;
             }
-            false
+            bool::from(matched).then_some(matched_timestamp)
         } else {
-            self.get_checksum(request, salt) == checksum
+            bool::from(hex_ct_eq(&self.get_checksum(request, salt), checksum)).then_some(0)
         }
     }
 }
 
 /// Everything in `get_checksum` before adding a timestamp
-fn pre_assemble(request: impl Request, shared_secret: &str, salt: &str) -> String {
+///
+/// `include_secret` appends `shared_secret` to the assembled string, reproducing the legacy
+/// `hash(.. ‖ shared_secret)` construction. When HMAC is used instead, the secret authenticates
+/// the message as a MAC key rather than being concatenated into it, so callers should pass
+/// `false`.
+///
+/// `canonical` switches from the legacy `key1value1key2value2...` concatenation to length-prefixing
+/// every field (`saltlen:saltkey1len:key1value1len:value1...`), removing the ambiguity of where one
+/// field ends and the next begins.
+fn pre_assemble(request: impl Request, shared_secret: &str, salt: &str, include_secret: bool, canonical: bool) -> String {
     let mut pairs = request.into_pairs();
 
     // Sort the dictionary alphanumerical by its keys.
     pairs.sort_by(|(k1, _), (k2, _)| k1.as_ref().cmp(k2.as_ref()));
 
-    // Concat its values to the respective key and join them: `key1value1key2value2...`
-    // Optional: Add a salt (this may be the method's endpoint): `saltkey1value1...`
-    // Append the shared secret of your target
-    let mut string = salt.to_string();
+    // Optional: Add a salt (this may be the method's endpoint).
+    // Concat its values to the respective key and join them.
+    // Append the shared secret of your target, unless it is used as an HMAC key instead.
+    let mut string = String::new();
+    append_field(&mut string, salt, canonical);
     for (key, value) in pairs.into_iter() {
-        write!(string, "{}", key.as_ref()).unwrap();
-        write!(string, "{}", value).unwrap();
+        append_field(&mut string, key.as_ref(), canonical);
+        append_field(&mut string, &value.to_string(), canonical);
+    }
+    if include_secret {
+        append_field(&mut string, shared_secret, canonical);
     }
-    write!(string, "{}", shared_secret).unwrap();
 
     string
 }
 
-/// Wrapper for computing a String's sha512
-///
-/// Effectivly everything in `get_checksum` after adding a timestamp
-fn sha512(data: impl AsRef<[u8]>) -> String {
-    let mut hasher = Sha512::new();
-    hasher.update(data);
-    let bytes = &hasher.finalize()[..];
-
-    let mut string = String::with_capacity(bytes.len() * 2);
-    for byte in bytes {
-        write!(string, "{:02x}", byte).unwrap();
+/// Append `field` to `string`, length-prefixing it as `len:field` when `canonical` is `true` so
+/// the boundary between fields can never be ambiguous.
+fn append_field(string: &mut String, field: &str, canonical: bool) {
+    if canonical {
+        write!(string, "{}:", field.len()).unwrap();
     }
-
-    string
+    string.push_str(field);
 }
 
 /// Trait implemented for all accepted payload types.
@@ -146,7 +217,7 @@ where
 mod test {
     use std::collections::HashMap;
     use std::fmt::Display;
-    use crate::RCPConfig;
+    use crate::{Algorithm, RCPConfig};
 
     /// Check the generic's ease of use with a few common types.
     ///
@@ -204,6 +275,9 @@ mod test {
             use_time_component: false,
             shared_secret: "Hallo-123".to_string(),
             time_delta: 5,
+            algorithm: Algorithm::Sha512,
+            use_hmac: false,
+            canonical: false,
         };
         let request = [("b", "test"), ("a", " long test")];
 
@@ -229,6 +303,9 @@ mod test {
             use_time_component: false,
             shared_secret: "Hallo-123".to_string(),
             time_delta: 5,
+            algorithm: Algorithm::Sha512,
+            use_hmac: false,
+            canonical: false,
         };
 
         // Output of reference implementation:
@@ -246,4 +323,58 @@ mod test {
         let checksum = "a85a29e01f295cba43de859a097b6f816826a0ef47bad9d210ab1410cc6ea8490f72a99e62c27b3aefd3b334b1a034d1b8ba1b8b0c6599c27674aeb96cebd591";
         assert_eq!(config.get_checksum([("b", "test"), ("a", " long test")], "TestSalt"), checksum);
     }
+
+    /// Check `HMAC-SHA512` output against a vector computed with python's `hmac` module
+    #[test]
+    fn get_checksum_hmac() {
+        let config = RCPConfig {
+            use_time_component: false,
+            shared_secret: "Hallo-123".to_string(),
+            time_delta: 5,
+            algorithm: Algorithm::Sha512,
+            use_hmac: true,
+            canonical: false,
+        };
+
+        // Output of reference implementation:
+        // hmac.new(b"Hallo-123", b"TestSalta long testbtest", hashlib.sha512).hexdigest()
+        let checksum = "2a55b4695eef163f4a81c1f7d9e6dce70f254c198ac6f43a165bc708dbb3bcf028156a98749801a807f1611284e0cead80972eb015f583b008d024f905c12fdc";
+        assert_eq!(config.get_checksum([("b", "test"), ("a", " long test")], "TestSalt"), checksum);
+        assert!(config.validate_checksum([("b", "test"), ("a", " long test")], "TestSalt", checksum));
+    }
+
+    /// `("ab", "c")` and `("a", "bc")` concatenate to the same string, so the legacy encoding
+    /// hashes them identically. `canonical` must tell them apart.
+    #[test]
+    fn canonical_resolves_key_value_ambiguity() {
+        let legacy = RCPConfig {
+            use_time_component: false,
+            ..RCPConfig::default()
+        };
+        assert_eq!(
+            legacy.get_checksum([("ab", "c")], ""),
+            legacy.get_checksum([("a", "bc")], ""),
+        );
+
+        let canonical = RCPConfig {
+            use_time_component: false,
+            canonical: true,
+            ..RCPConfig::default()
+        };
+        assert_ne!(
+            canonical.get_checksum([("ab", "c")], ""),
+            canonical.get_checksum([("a", "bc")], ""),
+        );
+    }
+
+    /// A multi-byte UTF-8 character in an even-length `checksum` must not make hex-decoding
+    /// panic on a split char boundary - it must simply fail to validate.
+    #[test]
+    fn validate_checksum_rejects_non_ascii_checksum() {
+        let config = RCPConfig {
+            use_time_component: false,
+            ..RCPConfig::default()
+        };
+        assert!(!config.validate_checksum([("a", "b")], "", "a€"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,141 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{RCPConfig, Request};
+
+/// Tracks which timestamps and checksums have already been accepted for a single partner, to
+/// close the replay window that [`RCPConfig::time_delta`] leaves open.
+///
+/// Within `time_delta` of now, a captured checksum is fully replayable: `RCPConfig` alone has no
+/// memory of what it has already seen. `ReplayGuard` closes this by remembering, per partner,
+/// (1) the highest timestamp it has ever accepted - anything strictly lower is refused outright -
+/// and (2) the exact `(timestamp, checksum)` pairs already accepted inside the live window, so an
+/// exact repeat is refused even while its timestamp is still fresh.
+///
+/// `ReplayGuard` implements `serde::Serialize`/`Deserialize` so it can be persisted to disk and
+/// reloaded, the same way this state would need to survive a restart.
+///
+/// **Requires `config.use_time_component` to be `true`.** Without a timestamp, there is no live
+/// window to expire old entries from, so `seen` would grow by one entry per distinct accepted
+/// checksum forever - a slow memory leak in exactly the long-running-server scenario this guard
+/// targets. `validate_checksum` asserts this rather than silently leaking memory; if you are not
+/// using timestamps, call [`RCPConfig::validate_checksum`] directly instead.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayGuard {
+    highest_timestamp: i64,
+    seen: BTreeSet<(i64, String)>,
+}
+impl ReplayGuard {
+    /// Create an empty guard that has not yet accepted anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validate `checksum` the way [`RCPConfig::validate_checksum`] does, but additionally
+    /// reject a timestamp older than any previously accepted one, as well as an exact repeat of
+    /// an already-accepted `(timestamp, checksum)` pair.
+    ///
+    /// On success, the matched timestamp/checksum pair is recorded so it cannot be replayed
+    /// again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.use_time_component` is `false` (see the type-level docs).
+    pub fn validate_checksum<R: Request>(
+        &mut self,
+        config: &RCPConfig,
+        request: R,
+        salt: &str,
+        checksum: &str,
+    ) -> bool {
+        assert!(
+            config.use_time_component,
+            "ReplayGuard requires RCPConfig::use_time_component to be true; \
+             without it there is no window to expire `seen` entries from. \
+             Use RCPConfig::validate_checksum directly instead.",
+        );
+
+        let timestamp = match config.matching_timestamp(request, salt, checksum) {
+            Some(timestamp) => timestamp,
+            None => return false,
+        };
+
+        if timestamp < self.highest_timestamp {
+            return false;
+        }
+
+        // `hex_ct_eq` decodes both sides before comparing, so e.g. "2a55b4" and "2A55B4" are the
+        // same checksum to it. Normalize to lowercase before keying `seen` on it, or a replay
+        // with one hex digit's case flipped would sail through as "new".
+        if !self.seen.insert((timestamp, checksum.to_lowercase())) {
+            return false;
+        }
+
+        if timestamp > self.highest_timestamp {
+            self.highest_timestamp = timestamp;
+
+            // Anything older than the live window can never validate again, so there is no
+            // point remembering it.
+            let cutoff = self.highest_timestamp - config.time_delta;
+            self.seen.retain(|(ts, _)| *ts >= cutoff);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{RCPConfig, ReplayGuard};
+
+    /// Check that a captured checksum cannot be replayed, even though `RCPConfig` alone would
+    /// happily accept it again and again.
+    #[test]
+    fn rejects_repeat() {
+        let config = RCPConfig {
+            use_time_component: true,
+            shared_secret: "Hallo-123".to_string(),
+            ..RCPConfig::default()
+        };
+        let request = [("b", "test"), ("a", " long test")];
+        let checksum = config.get_checksum(request, "TestSalt");
+
+        let mut guard = ReplayGuard::new();
+        assert!(guard.validate_checksum(&config, request, "TestSalt", &checksum));
+        assert!(!guard.validate_checksum(&config, request, "TestSalt", &checksum));
+    }
+
+    /// `hex_ct_eq` decodes hex case-insensitively, so flipping a checksum's case must not let an
+    /// already-accepted one back in as if it were new.
+    #[test]
+    fn rejects_repeat_with_different_case() {
+        let config = RCPConfig {
+            use_time_component: true,
+            shared_secret: "Hallo-123".to_string(),
+            ..RCPConfig::default()
+        };
+        let request = [("b", "test"), ("a", " long test")];
+        let checksum = config.get_checksum(request, "TestSalt");
+
+        let mut guard = ReplayGuard::new();
+        assert!(guard.validate_checksum(&config, request, "TestSalt", &checksum));
+        assert!(!guard.validate_checksum(&config, request, "TestSalt", &checksum.to_uppercase()));
+    }
+
+    /// Without a time component there is no window to expire `seen` entries from, so
+    /// `validate_checksum` refuses to run rather than silently leaking memory.
+    #[test]
+    #[should_panic(expected = "use_time_component")]
+    fn panics_without_time_component() {
+        let config = RCPConfig {
+            use_time_component: false,
+            shared_secret: "Hallo-123".to_string(),
+            ..RCPConfig::default()
+        };
+        let request = [("b", "test"), ("a", " long test")];
+        let checksum = config.get_checksum(request, "TestSalt");
+
+        ReplayGuard::new().validate_checksum(&config, request, "TestSalt", &checksum);
+    }
+}